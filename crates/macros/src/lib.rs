@@ -39,6 +39,21 @@ use std::{collections::BTreeSet, path::PathBuf};
 /// Instead of handling this yourself, you can use the [`cargo winrt`](https://github.com/microsoft/winrt-rs/tree/master/crates/cargo-winrt)
 /// helper subcommand.
 ///
+/// ## Standalone winmd files
+/// If you have a `.winmd` file that wasn't published to NuGet (in-house or
+/// experimental metadata, for example), point at it directly with `file:`.
+/// The path is resolved relative to the crate's manifest directory.
+///
+/// ```rust,ignore
+/// build!(
+///     dependencies
+///         os
+///         file: "../metadata/Custom.winmd"
+///     types
+///         custom::*
+/// );
+/// ```
+///
 /// ## Types
 /// After specifying the dependencies, you must then specify which types you want to use. These
 /// follow the same convention as Rust `use` paths. Types know which other types they depend on so
@@ -58,6 +73,33 @@ use std::{collections::BTreeSet, path::PathBuf};
 ///         microsoft::ai::machine_learning::*
 /// );
 /// ```
+///
+/// A whole namespace can be excluded from an otherwise broad selection by
+/// prefixing its use tree with `!`. This is useful for dropping a
+/// sub-namespace you don't want out of an otherwise-wanted tree.
+///
+/// ```rust,ignore
+/// build!(
+///     dependencies
+///         os
+///     types
+///         microsoft::ai::machine_learning::*
+///         !microsoft::ai::machine_learning::preview::*
+/// );
+/// ```
+///
+/// Excluding an individual type (rather than a whole namespace) isn't
+/// supported yet: that needs `TypeLimits` to grow longest-prefix
+/// include/exclude matching, which it doesn't have today.
+///
+/// ## Architecture-restricted types (not yet implemented)
+/// Some metadata types are only defined on a subset of architectures
+/// (`SupportedArchitectureAttribute`), and generating bindings on one host
+/// for a different target architecture can currently produce layout-
+/// incorrect or absent types. Gating those types' emitted definitions
+/// behind `#[cfg(target_arch = "...")]` would need `winrt_gen` to read the
+/// attribute while building the type tree and attach the cfg to the
+/// emitted tokens; neither exists yet, so no such gating happens today.
 #[proc_macro]
 pub fn build(stream: TokenStream) -> TokenStream {
     let import = parse_macro_input!(stream as ImportMacro);
@@ -67,7 +109,7 @@ pub fn build(stream: TokenStream) -> TokenStream {
         #(println!("cargo:rerun-if-changed={}", #winmd_paths);)*
     };
 
-    let tokens = match import.to_tokens_string() {
+    let types_tokens = match import.to_tokens_string() {
         Ok(t) => t,
         Err(t) => return t.into(),
     };
@@ -81,7 +123,7 @@ pub fn build(stream: TokenStream) -> TokenStream {
 
         path.push("winrt.rs");
         let mut file = ::std::fs::File::create(&path).expect("Failed to create winrt.rs");
-        file.write_all(#tokens.as_bytes()).expect("Could not write generated code to output file");
+        file.write_all(#types_tokens.as_bytes()).expect("Could not write generated code to output file");
 
         let mut cmd = ::std::process::Command::new("rustfmt");
         cmd.arg(&path);
@@ -110,6 +152,7 @@ impl ImportMacro {
         &self.dependencies.0
     }
 
+    /// Generates the `winrt.rs` source.
     fn to_tokens_string(self) -> Result<String, proc_macro2::TokenStream> {
         let dependencies = self.dependencies.0.iter().map(WinmdFile::new).collect();
 
@@ -134,7 +177,7 @@ impl ImportMacro {
             }
         }
 
-        for limit in self.types.0 {
+        for limit in self.types.includes {
             let types = limit.types;
             let syntax = limit.syntax;
             if let Err(e) = limits.insert(types).map_err(|ns| {
@@ -155,6 +198,17 @@ impl ImportMacro {
             tree.reexport();
         }
 
+        if !self.types.exclude_namespaces.is_empty() {
+            for namespace in &self.types.exclude_namespaces {
+                tree.remove(namespace);
+            }
+
+            // Mirrors the `foundation` exclusion above: anything still
+            // referencing a removed namespace needs to go through a
+            // re-export rather than be left as a dangling path.
+            tree.reexport();
+        }
+
         let ts = tree
             .to_tokens()
             .reduce(squote::TokenStream::new, |mut accum, n| {
@@ -168,9 +222,19 @@ impl ImportMacro {
 
 impl Parse for ImportMacro {
     fn parse(input: ParseStream) -> parse::Result<Self> {
-        let dependencies = Dependencies::parse()
+        let mut dependencies = Dependencies::parse()
             .map_err(|e| syn::Error::new(proc_macro2::Span::call_site(), format!("{}", e)))?;
-        let foundation = input.parse::<keywords::foundation>().is_ok();
+        dependencies.parse_declared(input)?;
+
+        let mut foundation = false;
+        loop {
+            if input.parse::<keywords::foundation>().is_ok() {
+                foundation = true;
+            } else {
+                break;
+            }
+        }
+
         let _ = input.parse::<keywords::types>()?;
         let types: TypesDeclarations = input.parse()?;
 
@@ -184,7 +248,9 @@ impl Parse for ImportMacro {
 
 /// keywords used in the `build!` macro
 mod keywords {
+    syn::custom_keyword!(os);
     syn::custom_keyword!(nuget);
+    syn::custom_keyword!(file);
     syn::custom_keyword!(dependencies);
     syn::custom_keyword!(types);
     syn::custom_keyword!(foundation);
@@ -226,10 +292,71 @@ impl Dependencies {
         }
         Ok(Dependencies(dependencies))
     }
+
+    /// Parses an optional `dependencies` section out of the `build!` macro's
+    /// token stream, adding any `file: "path/to/file.winmd"` entries on top
+    /// of the `os`/`nuget` dependencies already discovered from `Cargo.toml`.
+    /// `os` and `nuget: Some.Package` entries are accepted here too (they
+    /// match the dependencies above automatically) but otherwise ignored.
+    fn parse_declared(&mut self, input: ParseStream) -> parse::Result<()> {
+        if input.parse::<keywords::dependencies>().is_err() {
+            return Ok(());
+        }
+
+        loop {
+            if input.peek(keywords::foundation) || input.peek(keywords::types) || input.is_empty()
+            {
+                break;
+            }
+
+            if input.parse::<keywords::os>().is_ok() {
+                continue;
+            }
+
+            if input.parse::<keywords::nuget>().is_ok() {
+                input.parse::<syn::Token![:]>()?;
+                parse_dotted_ident(input)?;
+                continue;
+            }
+
+            if input.parse::<keywords::file>().is_ok() {
+                input.parse::<syn::Token![:]>()?;
+                let path = input.parse::<syn::LitStr>()?;
+                let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").map_err(|e| {
+                    syn::Error::new_spanned(&path, format!("CARGO_MANIFEST_DIR: {}", e))
+                })?;
+                self.0.insert(PathBuf::from(manifest_dir).join(path.value()));
+                continue;
+            }
+
+            return Err(input.error("expected `os`, `nuget: Some.Package`, or `file: \"path\"`"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a `.`-separated identifier path, e.g. `Microsoft.AI.MachineLearning`.
+fn parse_dotted_ident(input: ParseStream) -> parse::Result<String> {
+    let mut name = input.parse::<syn::Ident>()?.to_string();
+    while input.peek(syn::Token![.]) {
+        input.parse::<syn::Token![.]>()?;
+        name.push('.');
+        name.push_str(&input.parse::<syn::Ident>()?.to_string());
+    }
+    Ok(name)
 }
 
 /// A parsed `types` section of the `import!` macro
-struct TypesDeclarations(BTreeSet<TypesDeclaration>);
+struct TypesDeclarations {
+    /// The types (and namespace globs) to seed dependency resolution with.
+    includes: BTreeSet<TypesDeclaration>,
+    /// Namespaces to drop from the tree after it's built, via the existing
+    /// `Tree::remove` + `Tree::reexport`, mirroring how the
+    /// `Windows.Foundation*` namespaces are dropped when `foundation` isn't
+    /// requested.
+    exclude_namespaces: Vec<String>,
+}
 
 struct TypesDeclaration {
     types: NamespaceTypes,
@@ -264,18 +391,47 @@ impl TryFrom<syn::UseTree> for TypesDeclaration {
 
 impl Parse for TypesDeclarations {
     fn parse(input: ParseStream) -> parse::Result<Self> {
-        let mut limits = BTreeSet::new();
+        let mut includes = BTreeSet::new();
+        let mut exclude_namespaces = Vec::new();
         loop {
             if input.is_empty() {
                 break;
             }
 
+            let exclude = if input.peek(syn::Token![!]) {
+                input.parse::<syn::Token![!]>()?;
+                true
+            } else if input.peek(syn::Token![-]) {
+                input.parse::<syn::Token![-]>()?;
+                true
+            } else {
+                false
+            };
+
             let use_tree: syn::UseTree = input.parse()?;
             let limit: TypesDeclaration = use_tree.try_into()?;
 
-            limits.insert(limit);
+            if exclude {
+                match limit.types.limit {
+                    TypeLimit::All => exclude_namespaces.push(limit.types.namespace),
+                    TypeLimit::Some(_) => {
+                        return Err(syn::Error::new_spanned(
+                            limit.syntax,
+                            "excluding an individual type is not supported yet; only a whole \
+                             namespace (`!some::namespace::*`) can be excluded until `TypeLimits` \
+                             grows longest-prefix include/exclude matching",
+                        ))
+                    }
+                }
+                continue;
+            }
+
+            includes.insert(limit);
         }
-        Ok(Self(limits))
+        Ok(Self {
+            includes,
+            exclude_namespaces,
+        })
     }
 }
 
@@ -358,3 +514,57 @@ fn use_tree_to_namespace_types(use_tree: &syn::UseTree) -> parse::Result<Namespa
 
     recurse(use_tree, &mut String::new())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bang_prefix_excludes_a_whole_namespace() {
+        let decl: TypesDeclarations =
+            syn::parse_str("foo::bar::*\n!foo::bar::baz::*").unwrap();
+        assert_eq!(decl.includes.len(), 1);
+        assert_eq!(decl.exclude_namespaces, vec!["foo.bar.baz".to_string()]);
+    }
+
+    #[test]
+    fn dash_prefix_excludes_a_whole_namespace() {
+        let decl: TypesDeclarations =
+            syn::parse_str("foo::bar::*\n-foo::bar::baz::*").unwrap();
+        assert_eq!(decl.exclude_namespaces, vec!["foo.bar.baz".to_string()]);
+    }
+
+    #[test]
+    fn excluding_an_individual_type_is_rejected() {
+        let result: parse::Result<TypesDeclarations> = syn::parse_str("!foo::bar::{Baz}");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn file_dependency_resolves_relative_to_manifest_dir() {
+        std::env::set_var("CARGO_MANIFEST_DIR", "/some/crate");
+
+        let mut dependencies = Dependencies(BTreeSet::new());
+        syn::parse::Parser::parse_str(
+            |input: ParseStream| dependencies.parse_declared(input),
+            "dependencies file: \"../metadata/Custom.winmd\"",
+        )
+        .unwrap();
+
+        assert!(dependencies
+            .0
+            .contains(&PathBuf::from("/some/crate/../metadata/Custom.winmd")));
+    }
+
+    #[test]
+    fn os_and_nuget_entries_are_accepted_but_not_recorded() {
+        let mut dependencies = Dependencies(BTreeSet::new());
+        syn::parse::Parser::parse_str(
+            |input: ParseStream| dependencies.parse_declared(input),
+            "dependencies os nuget: Microsoft.AI.MachineLearning",
+        )
+        .unwrap();
+
+        assert!(dependencies.0.is_empty());
+    }
+}